@@ -1,118 +1,799 @@
 use axum::{
     Json, Router,
+    extract::State,
+    http::StatusCode,
     response::Json as ResponseJson,
     routing::{get, post},
 };
 use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
-use openai_api_rust::embeddings;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use tower_http::trace::TraceLayer;
+use std::collections::HashMap;
 use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tracing;
 
 const DEFAULT_SERVER_HOST: &str = "0.0.0.0";
 const DEFAULT_SERVER_PORT: &str = "8080";
 
-async fn root() -> &'static str {
-    "Hello, World!"
+/// Model name served when a request does not pin a specific model.
+const DEFAULT_MODEL: &str = "nomic-embed-text-v1.5";
+
+/// Number of inputs embedded per chunk when parallelizing large batches.
+const EMBED_CHUNK_SIZE: usize = 256;
+
+/// Default number of worker threads for batched embedding.
+const DEFAULT_REQUEST_PARALLELISM: usize = 4;
+
+/// Resolve the embedding thread-pool size from `REQUEST_PARALLELISM`.
+fn request_parallelism() -> usize {
+    env::var("REQUEST_PARALLELISM")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_REQUEST_PARALLELISM)
 }
 
-async fn embeddings_create(
-    Json(payload): Json<embeddings::EmbeddingsBody>,
-) -> ResponseJson<serde_json::Value> {
-    let model = TextEmbedding::try_new(
-        InitOptions::new(EmbeddingModel::NomicEmbedTextV15Q).with_show_download_progress(true),
-    )
-    .expect("Failed to initialize model");
+/// Embed `input` in fixed-size chunks across `pool`, reassembling the vectors
+/// in original index order. Bulk ingestion requests can carry thousands of
+/// inputs; chunking lets them embed in parallel while the pool size bounds how
+/// much work hits the local model at once. Remote sources do not go through
+/// this pool.
+fn embed_chunks(
+    model: &TextEmbedding,
+    input: Vec<String>,
+    chunk_size: usize,
+    pool: &rayon::ThreadPool,
+) -> Result<Vec<Vec<f32>>, fastembed::Error> {
+    let chunks: Vec<Vec<String>> = input
+        .chunks(chunk_size)
+        .map(|chunk| chunk.to_vec())
+        .collect();
 
-    let embeddings = model
-        .embed(payload.input, None)
-        .expect("failed to embed document");
+    // `par_iter().collect()` preserves input order, so the flattened result
+    // keeps each embedding aligned with its original position.
+    let results: Result<Vec<Vec<Vec<f32>>>, fastembed::Error> =
+        pool.install(|| chunks.into_par_iter().map(|chunk| model.embed(chunk, None)).collect());
 
-    // Only log detailed embedding information at trace level to reduce log volume
-    tracing::trace!("Embeddings length: {}", embeddings.len());
-    tracing::trace!("Embedding dimension: {}", embeddings[0].len());
-
-    // Log the first 10 values of the original embedding at trace level
-    tracing::trace!("Original embedding preview: {:?}", &embeddings[0][..10.min(embeddings[0].len())]);
-
-    // Check if there are any NaN or zero values in the original embedding
-    let nan_count = embeddings[0].iter().filter(|&&x| x.is_nan()).count();
-    let zero_count = embeddings[0].iter().filter(|&&x| x == 0.0).count();
-    tracing::trace!("Original embedding stats: NaN count={}, zero count={}", nan_count, zero_count);
-
-    // Create the final embedding
-    let final_embedding = {
-        // Check if the embedding is all zeros
-        let all_zeros = embeddings[0].iter().all(|&x| x == 0.0);
-        if all_zeros {
-            tracing::warn!("Embedding is all zeros. Generating random non-zero embedding.");
-
-            // Generate a random non-zero embedding
-            use rand::Rng;
-            let mut rng = rand::thread_rng();
-            let mut random_embedding = Vec::with_capacity(768);
-            for _ in 0..768 {
-                // Generate random values between -1.0 and 1.0, excluding 0
-                let mut val = 0.0;
-                while val == 0.0 {
-                    val = rng.gen_range(-1.0..1.0);
+    Ok(results?.into_iter().flatten().collect())
+}
+
+/// Request body for `/v1/embeddings`.
+///
+/// Mirrors the OpenAI embeddings request, plus the optional `dimensions`
+/// field used to request a reduced-dimension (Matryoshka) vector.
+#[derive(Debug, Deserialize, Serialize)]
+struct EmbeddingsRequest {
+    model: String,
+    input: Vec<String>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    dimensions: Option<usize>,
+}
+
+/// Metadata describing an embedding model this server can serve locally.
+///
+/// Each entry pairs the public name clients select by with the underlying
+/// fastembed variant and the native output dimension used to size and
+/// validate the returned vectors.
+struct ModelInfo {
+    /// Public name accepted in the request `model` field.
+    name: &'static str,
+    /// Underlying fastembed model variant.
+    model: EmbeddingModel,
+    /// Native output dimensionality of the model.
+    dimensions: usize,
+    /// Maximum number of input tokens the model accepts per string.
+    max_token: usize,
+}
+
+/// The embedding models this server can serve locally, in listing order.
+fn supported_models() -> Vec<ModelInfo> {
+    vec![
+        ModelInfo {
+            name: "nomic-embed-text-v1.5",
+            model: EmbeddingModel::NomicEmbedTextV15Q,
+            dimensions: 768,
+            max_token: 8192,
+        },
+        ModelInfo {
+            name: "bge-small-en-v1.5",
+            model: EmbeddingModel::BGESmallENV15Q,
+            dimensions: 384,
+            max_token: 512,
+        },
+        ModelInfo {
+            name: "all-MiniLM-L6-v2",
+            model: EmbeddingModel::AllMiniLML6V2Q,
+            dimensions: 384,
+            max_token: 256,
+        },
+    ]
+}
+
+/// Resolve a requested model name to its metadata, if supported.
+fn resolve_model(name: &str) -> Option<ModelInfo> {
+    supported_models().into_iter().find(|m| m.name == name)
+}
+
+/// Build an OpenAI-style error response body wrapped with an HTTP status.
+fn openai_error(
+    status: StatusCode,
+    message: String,
+    err_type: &str,
+    code: Option<&str>,
+) -> (StatusCode, ResponseJson<serde_json::Value>) {
+    let body = serde_json::json!({
+        "error": {
+            "message": message,
+            "type": err_type,
+            "param": serde_json::Value::Null,
+            "code": code,
+        }
+    });
+    (status, ResponseJson(body))
+}
+
+/// Where an embedding request is actually served from.
+///
+/// `Local` runs a fastembed model in-process; the remaining variants forward
+/// the request to a remote HTTP backend. `OpenAi` and `Ollama` are thin
+/// presets over the generic `Rest` path, so hyphalbase can act as a single
+/// embeddings gateway in front of several providers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum EmbedderSource {
+    Local,
+    OpenAi,
+    Ollama,
+    Rest,
+}
+
+impl EmbedderSource {
+    /// Resolve the source for a request from an optional `provider/name`
+    /// model prefix, falling back to the server's configured default. Returns
+    /// the source together with the effective model name passed downstream.
+    fn resolve(model: &str, default: EmbedderSource) -> (EmbedderSource, String) {
+        if let Some((prefix, name)) = model.split_once('/') {
+            let source = match prefix {
+                "local" => Some(EmbedderSource::Local),
+                "openai" => Some(EmbedderSource::OpenAi),
+                "ollama" => Some(EmbedderSource::Ollama),
+                "rest" => Some(EmbedderSource::Rest),
+                _ => None,
+            };
+            if let Some(source) = source {
+                return (source, name.to_string());
+            }
+        }
+        (default, model.to_string())
+    }
+
+    /// Default endpoint for a remote source when no URL is configured for it.
+    fn default_url(self) -> &'static str {
+        match self {
+            EmbedderSource::OpenAi => "https://api.openai.com/v1/embeddings",
+            EmbedderSource::Ollama => "http://localhost:11434/api/embed",
+            _ => "",
+        }
+    }
+}
+
+/// Endpoint URL and bearer token for one remote embedder source.
+#[derive(Clone, Default)]
+struct SourceOverride {
+    url: Option<String>,
+    api_key: Option<String>,
+}
+
+impl SourceOverride {
+    /// Fill in anything unset here from `fallback`, preferring this source's
+    /// own values.
+    fn or(self, fallback: SourceOverride) -> Self {
+        Self {
+            url: self.url.or(fallback.url),
+            api_key: self.api_key.or(fallback.api_key),
+        }
+    }
+}
+
+/// Configuration for the remote embedder backend, read once from the
+/// environment at startup.
+///
+/// A request can select its provider independently of the server's
+/// configured default via a `provider/model` prefix (see
+/// [`EmbedderSource::resolve`]), so each remote source keeps its own endpoint
+/// and credentials rather than sharing one pair that only applies to
+/// `source`.
+#[derive(Clone)]
+struct RemoteConfig {
+    /// Default source used when a request does not pin one via model prefix.
+    source: EmbedderSource,
+    openai: SourceOverride,
+    ollama: SourceOverride,
+    rest: SourceOverride,
+}
+
+impl RemoteConfig {
+    fn from_env() -> Self {
+        let source = match env::var("EMBEDDER_SOURCE").ok().as_deref() {
+            Some("openai") => EmbedderSource::OpenAi,
+            Some("ollama") => EmbedderSource::Ollama,
+            Some("rest") => EmbedderSource::Rest,
+            _ => EmbedderSource::Local,
+        };
+        // `EMBEDDER_URL`/`EMBEDDER_API_KEY` configure whichever source is the
+        // default, keeping existing single-provider deployments working
+        // unchanged. `OPENAI_URL`/`OPENAI_API_KEY`, `OLLAMA_URL`/
+        // `OLLAMA_API_KEY`, and `REST_URL`/`REST_API_KEY` configure that
+        // provider regardless of which one is the default, so a per-request
+        // model-prefix override still reaches its own endpoint with its own
+        // credentials.
+        let default_override = SourceOverride {
+            url: env::var("EMBEDDER_URL").ok(),
+            api_key: env::var("EMBEDDER_API_KEY").ok(),
+        };
+        let mut openai = SourceOverride {
+            url: env::var("OPENAI_URL").ok(),
+            api_key: env::var("OPENAI_API_KEY").ok(),
+        };
+        let mut ollama = SourceOverride {
+            url: env::var("OLLAMA_URL").ok(),
+            api_key: env::var("OLLAMA_API_KEY").ok(),
+        };
+        let mut rest = SourceOverride {
+            url: env::var("REST_URL").ok(),
+            api_key: env::var("REST_API_KEY").ok(),
+        };
+        match source {
+            EmbedderSource::OpenAi => openai = openai.or(default_override),
+            EmbedderSource::Ollama => ollama = ollama.or(default_override),
+            EmbedderSource::Rest => rest = rest.or(default_override),
+            EmbedderSource::Local => {}
+        }
+        Self { source, openai, ollama, rest }
+    }
+
+    /// The endpoint/credential override for `source`, if any.
+    fn override_for(&self, source: EmbedderSource) -> &SourceOverride {
+        match source {
+            EmbedderSource::OpenAi => &self.openai,
+            EmbedderSource::Ollama => &self.ollama,
+            EmbedderSource::Rest => &self.rest,
+            EmbedderSource::Local => unreachable!("local source has no remote override"),
+        }
+    }
+}
+
+/// A failure from a remote embedding backend, carrying the status to surface.
+struct RemoteError {
+    status: StatusCode,
+    message: String,
+}
+
+impl RemoteError {
+    fn into_response(self) -> (StatusCode, ResponseJson<serde_json::Value>) {
+        openai_error(self.status, self.message, "api_error", None)
+    }
+}
+
+/// Forward an embedding request to a remote HTTP backend and return the
+/// vectors in original input order, using a blocking `ureq::Agent`.
+fn remote_embed(
+    agent: &ureq::Agent,
+    source: EmbedderSource,
+    cfg: &RemoteConfig,
+    model: &str,
+    input: &[String],
+) -> Result<Vec<Vec<f32>>, RemoteError> {
+    // Each remote source carries its own endpoint/credential override, so a
+    // request that selects a different provider via its model prefix reaches
+    // that provider's own endpoint with its own credentials rather than the
+    // configured default's.
+    let over = cfg.override_for(source);
+    let url = over.url.clone().unwrap_or_else(|| source.default_url().to_string());
+    if url.is_empty() {
+        return Err(RemoteError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: format!("no endpoint configured for the {:?} source", source),
+        });
+    }
+    let api_key = over.api_key.clone();
+
+    let body = serde_json::json!({ "model": model, "input": input });
+    let mut req = agent.post(&url).set("content-type", "application/json");
+    if let Some(key) = &api_key {
+        req = req.set("Authorization", &format!("Bearer {}", key));
+    }
+
+    let response = req.send_json(body).map_err(|e| match e {
+        ureq::Error::Status(code, resp) => {
+            // Read the response body before it is dropped so the retry
+            // classifier can distinguish a context-length error from a plain
+            // 4xx; `into_string` consumes `resp`, so this is our only chance.
+            let body = resp.into_string().unwrap_or_default();
+            let message = extract_remote_error_detail(&body)
+                .unwrap_or_else(|| format!("remote embedder returned status {}", code));
+            RemoteError {
+                status: StatusCode::from_u16(code).unwrap_or(StatusCode::BAD_GATEWAY),
+                message,
+            }
+        }
+        other => RemoteError {
+            status: StatusCode::BAD_GATEWAY,
+            message: format!("remote embedder request failed: {}", other),
+        },
+    })?;
+
+    let value: serde_json::Value = response.into_json().map_err(|e| RemoteError {
+        status: StatusCode::BAD_GATEWAY,
+        message: format!("invalid JSON from remote embedder: {}", e),
+    })?;
+
+    parse_remote_embeddings(&value).ok_or_else(|| RemoteError {
+        status: StatusCode::BAD_GATEWAY,
+        message: "remote embedder response did not contain embeddings".to_string(),
+    })
+}
+
+/// Pull a human-readable detail out of a remote error response body.
+///
+/// Normalizes an OpenAI-style context-length error into the "too many tokens"
+/// phrasing that [`RetryStrategy::classify`] keys on, so an oversized batch is
+/// split and retried rather than surfaced as a flat 4xx.
+fn extract_remote_error_detail(body: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let error = value.get("error")?;
+    let code = error.get("code").and_then(|c| c.as_str()).unwrap_or("");
+    let message = error.get("message").and_then(|m| m.as_str()).unwrap_or("");
+
+    if code == "context_length_exceeded"
+        || message.to_lowercase().contains("maximum context length")
+        || message.to_lowercase().contains("too many tokens")
+    {
+        return Some(format!("too many tokens: {}", message));
+    }
+    if !message.is_empty() {
+        return Some(message.to_string());
+    }
+    None
+}
+
+/// Maximum number of attempts before a remote embedding call gives up.
+const MAX_EMBED_ATTEMPTS: u32 = 4;
+
+/// How a failed remote attempt should be handled: give up, retry as-is,
+/// retry with the batch split, or retry after a rate-limit-specific backoff.
+enum RetryStrategy {
+    /// Terminal failure (e.g. auth error); surface immediately.
+    GiveUp,
+    /// Transient failure; retry after an exponential backoff.
+    Retry,
+    /// Input too large; retry with the batch split into smaller chunks.
+    RetryTokenized,
+    /// Rate-limited; retry after a backoff with an added floor.
+    RetryAfterRateLimit,
+}
+
+impl RetryStrategy {
+    /// Classify a remote failure into a retry decision.
+    fn classify(err: &RemoteError) -> RetryStrategy {
+        if err.message.contains("too many tokens") {
+            return RetryStrategy::RetryTokenized;
+        }
+        match err.status.as_u16() {
+            401 => RetryStrategy::GiveUp,
+            429 => RetryStrategy::RetryAfterRateLimit,
+            code if code >= 500 => RetryStrategy::Retry,
+            _ => RetryStrategy::GiveUp,
+        }
+    }
+
+    /// Milliseconds to sleep before attempt `n` (1-based): roughly `10^n` ms,
+    /// plus a 100ms floor once a rate-limit response has been seen.
+    fn backoff_ms(&self, n: u32) -> u64 {
+        let base = 10u64.pow(n);
+        match self {
+            RetryStrategy::RetryAfterRateLimit => 100 + base,
+            _ => base,
+        }
+    }
+}
+
+/// Forward an embedding request to a remote backend, retrying transient and
+/// rate-limited failures with backoff and splitting oversized batches, instead
+/// of panicking. Returns a terminal `RemoteError` once attempts are exhausted.
+fn remote_embed_with_retry(
+    agent: &ureq::Agent,
+    source: EmbedderSource,
+    cfg: &RemoteConfig,
+    model: &str,
+    input: &[String],
+) -> Result<Vec<Vec<f32>>, RemoteError> {
+    let mut attempt = 1;
+    loop {
+        let err = match remote_embed(agent, source, cfg, model, input) {
+            Ok(vectors) => return Ok(vectors),
+            Err(err) => err,
+        };
+
+        match RetryStrategy::classify(&err) {
+            RetryStrategy::GiveUp => return Err(err),
+            RetryStrategy::RetryTokenized => {
+                // Oversized input: split the batch and embed each half
+                // independently, reassembling in order. A single input cannot
+                // be split here, so its error is returned as-is.
+                if input.len() <= 1 {
+                    return Err(err);
                 }
-                random_embedding.push(val);
+                let mid = input.len() / 2;
+                let mut head = remote_embed_with_retry(agent, source, cfg, model, &input[..mid])?;
+                let tail = remote_embed_with_retry(agent, source, cfg, model, &input[mid..])?;
+                head.extend(tail);
+                return Ok(head);
             }
+            strategy => {
+                if attempt >= MAX_EMBED_ATTEMPTS {
+                    return Err(RemoteError {
+                        status: StatusCode::BAD_GATEWAY,
+                        message: format!(
+                            "remote embedder failed after {} attempts: {}",
+                            attempt, err.message
+                        ),
+                    });
+                }
+                let sleep = strategy.backoff_ms(attempt + 1);
+                tracing::warn!(
+                    "remote embedder attempt {} failed ({}); retrying in {}ms",
+                    attempt,
+                    err.message,
+                    sleep
+                );
+                std::thread::sleep(Duration::from_millis(sleep));
+                attempt += 1;
+            }
+        }
+    }
+}
 
-            // Normalize the random embedding
-            let norm: f32 = random_embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
-            for i in 0..random_embedding.len() {
-                random_embedding[i] /= norm;
+/// Extract embedding vectors from the various remote response shapes: OpenAI
+/// (`data[].embedding`), Ollama `/api/embed` (`embeddings`), and the Ollama
+/// single-prompt shape (`embedding`).
+fn parse_remote_embeddings(value: &serde_json::Value) -> Option<Vec<Vec<f32>>> {
+    let as_vec = |v: &serde_json::Value| -> Option<Vec<f32>> {
+        v.as_array()
+            .map(|xs| xs.iter().filter_map(|x| x.as_f64().map(|f| f as f32)).collect())
+    };
+
+    if let Some(data) = value.get("data").and_then(|d| d.as_array()) {
+        return Some(data.iter().filter_map(|d| as_vec(d.get("embedding")?)).collect());
+    }
+    if let Some(list) = value.get("embeddings").and_then(|e| e.as_array()) {
+        return Some(list.iter().filter_map(as_vec).collect());
+    }
+    if let Some(single) = value.get("embedding") {
+        return as_vec(single).map(|v| vec![v]);
+    }
+    None
+}
+
+/// Shared application state threaded through axum handlers.
+///
+/// Each `TextEmbedding` model is expensive to initialize (it downloads and
+/// loads weights), so models are built once and cached for reuse across
+/// requests. They are held behind a `Mutex` because `embed` takes `&self` but
+/// the model is not cheaply clonable, so a single warm instance is shared
+/// rather than rebuilt on every request.
+#[derive(Clone)]
+struct AppState {
+    models: Arc<Mutex<HashMap<String, Arc<Mutex<TextEmbedding>>>>>,
+    remote: RemoteConfig,
+    http: ureq::Agent,
+    /// BPE tokenizer used to count input tokens for `usage` and to reject
+    /// inputs past a model's context limit before embedding.
+    tokenizer: Arc<tiktoken_rs::CoreBPE>,
+    /// Bounded thread pool used to embed large batches chunk-by-chunk.
+    pool: Arc<rayon::ThreadPool>,
+}
+
+impl AppState {
+    /// Initialize the state and, when the default source is local, best-effort
+    /// warm the default model so the first request does not pay the
+    /// initialization cost. A failed warm-up (e.g. no network route to fetch
+    /// weights) is logged and does not stop the server from starting; the
+    /// model is loaded lazily on the first request that actually needs it.
+    fn new() -> Self {
+        let remote = RemoteConfig::from_env();
+        let state = Self {
+            models: Arc::new(Mutex::new(HashMap::new())),
+            http: ureq::AgentBuilder::new().build(),
+            tokenizer: Arc::new(
+                tiktoken_rs::cl100k_base().expect("failed to load cl100k_base tokenizer"),
+            ),
+            pool: Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(request_parallelism())
+                    .build()
+                    .expect("failed to build embedding thread pool"),
+            ),
+            remote,
+        };
+        if state.remote.source == EmbedderSource::Local {
+            let default = resolve_model(DEFAULT_MODEL).expect("default model is supported");
+            if let Err(e) = state.model_for(&default) {
+                tracing::warn!(
+                    "failed to warm default model `{}`, will retry lazily on first request: {}",
+                    default.name,
+                    e
+                );
             }
+        }
+        state
+    }
 
-            random_embedding
-        } else {
-            // Check if dimensions parameter is provided and pad the embeddings if necessary
-            let mut padded_embedding = embeddings[0].clone();
-
-            // If the client expects 768 dimensions but our model produces fewer, pad with zeros
-            let target_dimension = 768;
-            if padded_embedding.len() < target_dimension {
-                let padding_needed = target_dimension - padded_embedding.len();
-                tracing::trace!("Padding embedding with {} zeros to reach {} dimensions", padding_needed, target_dimension);
-                padded_embedding.extend(vec![0.0; padding_needed]);
+    /// Return the warm model for `info`, loading and caching it on first use.
+    ///
+    /// The cache lock is only held to check for and record a warm instance;
+    /// `TextEmbedding::try_new` (which can download and load weights) runs
+    /// outside it, so a cold load of one model does not block requests for
+    /// every other model already in the cache. A load failure is returned to
+    /// the caller rather than panicking, so it cannot take down requests for
+    /// other models or, during warm-up, the server itself.
+    fn model_for(&self, info: &ModelInfo) -> Result<Arc<Mutex<TextEmbedding>>, fastembed::Error> {
+        if let Some(model) = self.models.lock().expect("model cache mutex poisoned").get(info.name) {
+            return Ok(Arc::clone(model));
+        }
+
+        let model = TextEmbedding::try_new(
+            InitOptions::new(info.model.clone()).with_show_download_progress(true),
+        )?;
+        let model = Arc::new(Mutex::new(model));
+
+        let mut cache = self.models.lock().expect("model cache mutex poisoned");
+        // Another request may have raced us and already loaded this model;
+        // keep its instance rather than replacing an already-warm one.
+        Ok(Arc::clone(cache.entry(info.name.to_string()).or_insert(model)))
+    }
+}
+
+async fn root() -> &'static str {
+    "Hello, World!"
+}
+
+/// List the models this server can serve, in the OpenAI `/v1/models` shape.
+async fn list_models() -> ResponseJson<serde_json::Value> {
+    let data: Vec<serde_json::Value> = supported_models()
+        .iter()
+        .map(|m| {
+            serde_json::json!({
+                "id": m.name,
+                "object": "model",
+                "owned_by": "hyphalbase",
+                "dimensions": m.dimensions,
+            })
+        })
+        .collect();
+    ResponseJson(serde_json::json!({ "object": "list", "data": data }))
+}
+
+/// Post-process one raw embedding into the vector returned to the client:
+/// substitute a random unit vector for an all-zero embedding, pad short
+/// vectors up to the target dimension, then apply optional Matryoshka
+/// reduction (truncate to `dimensions` and re-normalize to unit L2 length).
+fn finalize_embedding(raw: &[f32], target_dimension: usize, dimensions: Option<usize>) -> Vec<f32> {
+    let mut final_embedding = if raw.iter().all(|&x| x == 0.0) {
+        tracing::warn!("Embedding is all zeros. Generating random non-zero embedding.");
+
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        let mut random_embedding = Vec::with_capacity(target_dimension);
+        for _ in 0..target_dimension {
+            // Generate random values between -1.0 and 1.0, excluding 0
+            let mut val = 0.0;
+            while val == 0.0 {
+                val = rng.gen_range(-1.0..1.0);
+            }
+            random_embedding.push(val);
+        }
+
+        let norm: f32 = random_embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        for value in random_embedding.iter_mut() {
+            *value /= norm;
+        }
+        random_embedding
+    } else {
+        let mut padded_embedding = raw.to_vec();
+        if padded_embedding.len() < target_dimension {
+            let padding_needed = target_dimension - padded_embedding.len();
+            padded_embedding.extend(vec![0.0; padding_needed]);
+        }
+        padded_embedding
+    };
+
+    // Matryoshka reduction: take the first `dimensions` components and
+    // re-normalize to unit L2 length so cosine similarity stays meaningful.
+    if let Some(dims) = dimensions {
+        final_embedding.truncate(dims);
+        let norm: f32 = final_embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            for value in final_embedding.iter_mut() {
+                *value /= norm;
             }
+        }
+    }
+
+    final_embedding
+}
+
+async fn embeddings_create(
+    State(state): State<AppState>,
+    Json(payload): Json<EmbeddingsRequest>,
+) -> Result<ResponseJson<serde_json::Value>, (StatusCode, ResponseJson<serde_json::Value>)> {
+    let (source, effective_model) = EmbedderSource::resolve(&payload.model, state.remote.source);
+
+    // Count tokens per input up front so `usage` reflects real counts and
+    // oversized inputs can be rejected before embedding.
+    let token_counts: Vec<usize> = payload
+        .input
+        .iter()
+        .map(|text| state.tokenizer.encode_ordinary(text).len())
+        .collect();
+    let total_tokens: usize = token_counts.iter().sum();
 
-            padded_embedding
+    // `embeddings` holds the raw vectors; `target_dimension` is the dimension
+    // the response is sized to (native dimension for local models, the backend
+    // output length for remote sources).
+    let (embeddings, target_dimension) = if source == EmbedderSource::Local {
+        let info = resolve_model(&effective_model).ok_or_else(|| {
+            openai_error(
+                StatusCode::BAD_REQUEST,
+                format!("The model `{}` does not exist", payload.model),
+                "invalid_request_error",
+                Some("model_not_found"),
+            )
+        })?;
+        // Reject reduced-dimension requests that ask for more than the model
+        // natively produces before paying for embedding; `info.dimensions` is
+        // already known here, so there is no need to wait for the embed call
+        // to reject an invalid request.
+        if let Some(dims) = payload.dimensions {
+            if dims == 0 || dims > info.dimensions {
+                return Err(openai_error(
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "dimensions {} must be between 1 and the model's native size {}",
+                        dims, info.dimensions
+                    ),
+                    "invalid_request_error",
+                    Some("invalid_dimensions"),
+                ));
+            }
         }
+        // Guard each input against the model's context limit. Chunking is not
+        // enabled, so an oversized input is a client error.
+        if let Some((idx, &count)) = token_counts
+            .iter()
+            .enumerate()
+            .find(|(_, &count)| count > info.max_token)
+        {
+            return Err(openai_error(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "input {} has too many tokens: {} exceeds the model's maximum of {}",
+                    idx, count, info.max_token
+                ),
+                "invalid_request_error",
+                Some("too_many_tokens"),
+            ));
+        }
+
+        let model_handle = state.model_for(&info).map_err(|e| {
+            openai_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("failed to load model `{}`: {}", info.name, e),
+                "api_error",
+                None,
+            )
+        })?;
+        let embeddings = {
+            let model = model_handle.lock().expect("embedding model mutex poisoned");
+            embed_chunks(&model, payload.input, EMBED_CHUNK_SIZE, &state.pool).map_err(|e| {
+                openai_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("failed to embed document: {}", e),
+                    "api_error",
+                    None,
+                )
+            })?
+        };
+        (embeddings, info.dimensions)
+    } else {
+        // The remote path makes blocking `ureq` calls and sleeps between
+        // retries; run it on the blocking pool so retrying requests don't park
+        // the async worker threads and starve the rest of the server.
+        let agent = state.http.clone();
+        let remote = state.remote.clone();
+        let model = effective_model;
+        let input = payload.input;
+        let embeddings = tokio::task::spawn_blocking(move || {
+            remote_embed_with_retry(&agent, source, &remote, &model, &input)
+        })
+        .await
+        .map_err(|e| {
+            openai_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("embedding task failed: {}", e),
+                "api_error",
+                None,
+            )
+        })?
+        .map_err(RemoteError::into_response)?;
+        let dim = embeddings.first().map(|e| e.len()).unwrap_or(0);
+        (embeddings, dim)
     };
 
-    tracing::trace!("Final embedding dimension: {}", final_embedding.len());
+    // For the local path this was already validated against `info.dimensions`
+    // before embedding; the remote output length is only known once the
+    // backend has replied, so it is checked here instead.
+    if source != EmbedderSource::Local {
+        if let Some(dims) = payload.dimensions {
+            if dims == 0 || dims > target_dimension {
+                return Err(openai_error(
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "dimensions {} must be between 1 and the model's native size {}",
+                        dims, target_dimension
+                    ),
+                    "invalid_request_error",
+                    Some("invalid_dimensions"),
+                ));
+            }
+        }
+    }
 
-    // Log the first 10 values of the final embedding at trace level
-    tracing::trace!("Final embedding preview: {:?}", &final_embedding[..10.min(final_embedding.len())]);
+    // Only log detailed embedding information at trace level to reduce log volume
+    tracing::trace!("Embeddings length: {}", embeddings.len());
+
+    // Finalize each embedding, preserving the original input index.
+    let data: Vec<serde_json::Value> = embeddings
+        .iter()
+        .enumerate()
+        .map(|(index, raw)| {
+            let embedding = finalize_embedding(raw, target_dimension, payload.dimensions);
+            serde_json::json!({
+                "object": "embedding",
+                "index": index,
+                "embedding": embedding,
+            })
+        })
+        .collect();
 
     // Return a response that matches the OpenAI API format
     let response = serde_json::json!({
         "object": "list",
-        "data": [
-            {
-                "object": "embedding",
-                "index": 0,
-                "embedding": final_embedding
-            }
-        ],
+        "data": data,
         "model": payload.model,
         "usage": {
-            "prompt_tokens": 0,
-            "total_tokens": 0
+            "prompt_tokens": total_tokens,
+            "total_tokens": total_tokens
         }
     });
-    ResponseJson(response)
+    Ok(ResponseJson(response))
 }
 
 fn create_app() -> Router {
+    create_app_with_state(AppState::new())
+}
+
+fn create_app_with_state(state: AppState) -> Router {
 	Router::new()
         .route("/", get(root))
         .route("/v1/embeddings", post(embeddings_create))
+        .route("/v1/models", get(list_models))
         .layer(TraceLayer::new_for_http())
+        .with_state(state)
 }
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 #[tokio::main]
@@ -180,7 +861,7 @@ mod tests {
         let openai = OpenAI::new(auth, &base_url);
 
         let body = EmbeddingsBody {
-            model: "nomic-text-embed".to_string(),
+            model: "nomic-embed-text-v1.5".to_string(),
             input: vec!["The food was delicious and the waiter...".to_string()],
             user: None,
         };
@@ -205,7 +886,7 @@ mod tests {
         assert_eq!(response_json["object"], "list");
         assert!(response_json["data"].is_array());
         assert_eq!(response_json["data"].as_array().unwrap().len(), 1);
-        assert_eq!(response_json["model"], "nomic-text-embed");
+        assert_eq!(response_json["model"], "nomic-embed-text-v1.5");
 
         let embedding_obj = &response_json["data"][0];
         assert_eq!(embedding_obj["object"], "embedding");
@@ -215,4 +896,390 @@ mod tests {
         let embedding = embedding_obj["embedding"].as_array().unwrap();
         assert_eq!(embedding.len(), 768);
     }
+
+    #[tokio::test]
+    async fn test_embeddings_unknown_model() {
+        let app = create_app();
+
+        let body = EmbeddingsBody {
+            model: "does-not-exist".to_string(),
+            input: vec!["hello".to_string()],
+            user: None,
+        };
+
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .method(axum::http::Method::POST)
+                    .uri("/v1/embeddings")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_string(&body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let response_json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response_json["error"]["code"], "model_not_found");
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_dimensions_truncate() {
+        let app = create_app();
+
+        let body = serde_json::json!({
+            "model": "nomic-embed-text-v1.5",
+            "input": ["The food was delicious and the waiter..."],
+            "dimensions": 256,
+        });
+
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .method(axum::http::Method::POST)
+                    .uri("/v1/embeddings")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_string(&body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let response_json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let embedding = response_json["data"][0]["embedding"].as_array().unwrap();
+        assert_eq!(embedding.len(), 256);
+
+        // The truncated vector must be re-normalized to unit L2 length.
+        let norm: f32 = embedding
+            .iter()
+            .map(|v| v.as_f64().unwrap() as f32)
+            .map(|v| v * v)
+            .sum::<f32>()
+            .sqrt();
+        assert!((norm - 1.0).abs() < 1e-3, "expected unit norm, got {}", norm);
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_batch_preserves_order() {
+        let app = create_app();
+
+        let body = serde_json::json!({
+            "model": "nomic-embed-text-v1.5",
+            "input": ["first input", "second input", "third input"],
+        });
+
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .method(axum::http::Method::POST)
+                    .uri("/v1/embeddings")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_string(&body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let response_json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let data = response_json["data"].as_array().unwrap();
+        assert_eq!(data.len(), 3);
+        for (i, item) in data.iter().enumerate() {
+            assert_eq!(item["index"], i);
+            assert!(item["embedding"].is_array());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_reports_token_usage() {
+        let app = create_app();
+
+        let body = serde_json::json!({
+            "model": "nomic-embed-text-v1.5",
+            "input": ["The food was delicious and the waiter..."],
+        });
+
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .method(axum::http::Method::POST)
+                    .uri("/v1/embeddings")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_string(&body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let response_json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let total = response_json["usage"]["total_tokens"].as_u64().unwrap();
+        assert!(total > 0, "expected non-zero token usage, got {}", total);
+        assert_eq!(response_json["usage"]["prompt_tokens"], response_json["usage"]["total_tokens"]);
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_dimensions_too_large() {
+        let app = create_app();
+
+        let body = serde_json::json!({
+            "model": "nomic-embed-text-v1.5",
+            "input": ["hello"],
+            "dimensions": 4096,
+        });
+
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .method(axum::http::Method::POST)
+                    .uri("/v1/embeddings")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_string(&body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let response_json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response_json["error"]["code"], "invalid_dimensions");
+    }
+
+    #[tokio::test]
+    async fn test_list_models() {
+        let app = create_app();
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/v1/models")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let response_json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response_json["object"], "list");
+        let ids: Vec<&str> = response_json["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["id"].as_str().unwrap())
+            .collect();
+        assert!(ids.contains(&"nomic-embed-text-v1.5"));
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_multi_input_not_collapsed() {
+        // Regression: earlier revisions built the response from `embeddings[0]`
+        // only, dropping every input past the first. Two distinct inputs must
+        // yield two distinct embeddings, one per index.
+        let app = create_app();
+
+        let body = serde_json::json!({
+            "model": "nomic-embed-text-v1.5",
+            "input": ["a sentence about cats", "a completely different topic: finance"],
+        });
+
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .method(axum::http::Method::POST)
+                    .uri("/v1/embeddings")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_string(&body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let response_json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let data = response_json["data"].as_array().unwrap();
+        assert_eq!(data.len(), 2);
+        assert_ne!(
+            data[0]["embedding"], data[1]["embedding"],
+            "distinct inputs collapsed to the same embedding"
+        );
+    }
+
+    #[test]
+    fn test_embedder_source_resolve_prefix() {
+        assert_eq!(
+            EmbedderSource::resolve("openai/text-embedding-3-small", EmbedderSource::Local),
+            (EmbedderSource::OpenAi, "text-embedding-3-small".to_string())
+        );
+        assert_eq!(
+            EmbedderSource::resolve("ollama/nomic-embed-text", EmbedderSource::Local),
+            (EmbedderSource::Ollama, "nomic-embed-text".to_string())
+        );
+        // A name with no provider prefix keeps the default source unchanged.
+        assert_eq!(
+            EmbedderSource::resolve("bge-small-en-v1.5", EmbedderSource::OpenAi),
+            (EmbedderSource::OpenAi, "bge-small-en-v1.5".to_string())
+        );
+    }
+
+    /// A mock remote backend that accepts one request, records the
+    /// `Authorization` header it was sent, and replies with a single embedding.
+    fn spawn_mock_embedder() -> (std::net::SocketAddr, Arc<Mutex<Option<String>>>) {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind mock listener");
+        let addr = listener.local_addr().expect("failed to read mock listener addr");
+        let captured_auth = Arc::new(Mutex::new(None));
+        let captured = Arc::clone(&captured_auth);
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("mock listener accept failed");
+            let mut buf = [0u8; 4096];
+            let n = stream.read(&mut buf).unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let auth = request
+                .lines()
+                .map(|line| line.trim_end_matches('\r'))
+                .find(|line| line.to_lowercase().starts_with("authorization:"))
+                .map(|line| line.to_string());
+            *captured.lock().expect("captured auth mutex poisoned") = auth;
+
+            let body = serde_json::json!({ "embedding": [1.0, 2.0] }).to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+        });
+
+        (addr, captured_auth)
+    }
+
+    #[test]
+    fn test_remote_embed_prefix_override_uses_matching_source_credentials() {
+        // The server's configured default source is Local, with no
+        // EMBEDDER_URL/EMBEDDER_API_KEY set; only OPENAI_URL/OPENAI_API_KEY
+        // target the OpenAi source. A request prefixed `openai/...` must still
+        // reach the mock endpoint with the OpenAi-specific key rather than
+        // failing (no endpoint) or reaching the real provider unauthenticated.
+        let (addr, captured_auth) = spawn_mock_embedder();
+        let cfg = RemoteConfig {
+            source: EmbedderSource::Local,
+            openai: SourceOverride {
+                url: Some(format!("http://{}", addr)),
+                api_key: Some("openai-only-key".to_string()),
+            },
+            ollama: SourceOverride::default(),
+            rest: SourceOverride::default(),
+        };
+        let agent = ureq::AgentBuilder::new().build();
+
+        let result = remote_embed(
+            &agent,
+            EmbedderSource::OpenAi,
+            &cfg,
+            "text-embedding-3-small",
+            &["hello".to_string()],
+        );
+
+        assert!(result.is_ok(), "expected ok, got {:?}", result.err().map(|e| e.message));
+        assert_eq!(
+            captured_auth.lock().expect("captured auth mutex poisoned").as_deref(),
+            Some("Authorization: Bearer openai-only-key")
+        );
+    }
+
+    #[test]
+    fn test_parse_remote_embeddings_openai_shape() {
+        let value = serde_json::json!({
+            "data": [
+                { "embedding": [0.1, 0.2] },
+                { "embedding": [0.3, 0.4] }
+            ]
+        });
+        let parsed = parse_remote_embeddings(&value).unwrap();
+        assert_eq!(parsed, vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+    }
+
+    #[test]
+    fn test_parse_remote_embeddings_ollama_shapes() {
+        let batch = serde_json::json!({ "embeddings": [[1.0, 2.0], [3.0, 4.0]] });
+        assert_eq!(
+            parse_remote_embeddings(&batch).unwrap(),
+            vec![vec![1.0, 2.0], vec![3.0, 4.0]]
+        );
+
+        let single = serde_json::json!({ "embedding": [5.0, 6.0] });
+        assert_eq!(parse_remote_embeddings(&single).unwrap(), vec![vec![5.0, 6.0]]);
+
+        assert!(parse_remote_embeddings(&serde_json::json!({ "foo": 1 })).is_none());
+    }
+
+    #[test]
+    fn test_extract_remote_error_detail_normalizes_context_length() {
+        let body = serde_json::json!({
+            "error": {
+                "code": "context_length_exceeded",
+                "message": "This model's maximum context length is 8192 tokens"
+            }
+        })
+        .to_string();
+        let detail = extract_remote_error_detail(&body).unwrap();
+        assert!(detail.contains("too many tokens"));
+    }
+
+    #[test]
+    fn test_retry_strategy_classify() {
+        let classify = |status: StatusCode, message: &str| {
+            RetryStrategy::classify(&RemoteError {
+                status,
+                message: message.to_string(),
+            })
+        };
+
+        assert!(matches!(
+            classify(StatusCode::UNAUTHORIZED, "bad key"),
+            RetryStrategy::GiveUp
+        ));
+        assert!(matches!(
+            classify(StatusCode::TOO_MANY_REQUESTS, "slow down"),
+            RetryStrategy::RetryAfterRateLimit
+        ));
+        assert!(matches!(
+            classify(StatusCode::BAD_GATEWAY, "upstream down"),
+            RetryStrategy::Retry
+        ));
+        assert!(matches!(
+            classify(StatusCode::BAD_REQUEST, "too many tokens: ..."),
+            RetryStrategy::RetryTokenized
+        ));
+        assert!(matches!(
+            classify(StatusCode::BAD_REQUEST, "bad input"),
+            RetryStrategy::GiveUp
+        ));
+    }
+
+    #[test]
+    fn test_retry_strategy_backoff_ms() {
+        // Ordinary retries grow as 10^n ms.
+        assert_eq!(RetryStrategy::Retry.backoff_ms(1), 10);
+        assert_eq!(RetryStrategy::Retry.backoff_ms(2), 100);
+        // Rate-limited retries add a 100ms floor.
+        assert_eq!(RetryStrategy::RetryAfterRateLimit.backoff_ms(1), 110);
+        assert_eq!(RetryStrategy::RetryAfterRateLimit.backoff_ms(2), 200);
+    }
 }